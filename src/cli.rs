@@ -5,6 +5,8 @@ use tokio::{
     net::UnixStream,
 };
 
+use crate::manager::Rule;
+
 /// nsticky CLI client
 #[derive(Parser, Debug)]
 #[command(name = "nsticky")]
@@ -26,6 +28,24 @@ enum Commands {
     },
     List,
     ToggleActive,
+    /// Automatically stick any newly-opened window matching this rule
+    StickRule {
+        /// Regex matched against the window's app_id
+        #[arg(long)]
+        app_id: Option<String>,
+        /// Substring matched against the window's title
+        #[arg(long)]
+        title: Option<String>,
+    },
+    /// Remove a previously registered auto-stick rule
+    UnstickRule {
+        #[arg(long)]
+        app_id: Option<String>,
+        #[arg(long)]
+        title: Option<String>,
+    },
+    /// Stream live sticky-state change events until interrupted
+    Monitor,
 }
 
 pub async fn run_cli() -> Result<()> {
@@ -42,14 +62,35 @@ pub async fn run_cli() -> Result<()> {
         Commands::Remove { window_id } => format!("remove {window_id}\n"),
         Commands::List => "list\n".to_string(),
         Commands::ToggleActive => "toggle_active\n".to_string(),
+        Commands::StickRule { app_id, title } => {
+            if app_id.is_none() && title.is_none() {
+                anyhow::bail!("stick-rule needs --app-id and/or --title");
+            }
+            let rule = serde_json::to_string(&Rule { app_id, title })?;
+            format!("stick_rule {rule}\n")
+        }
+        Commands::UnstickRule { app_id, title } => {
+            if app_id.is_none() && title.is_none() {
+                anyhow::bail!("unstick-rule needs --app-id and/or --title");
+            }
+            let rule = serde_json::to_string(&Rule { app_id, title })?;
+            format!("unstick_rule {rule}\n")
+        }
+        Commands::Monitor => "monitor\n".to_string(),
     };
 
     writer.write_all(cmd_str.as_bytes()).await?;
     writer.flush().await?;
 
     let mut response = String::new();
-    reader.read_line(&mut response).await?;
-    print!("{response}");
+    loop {
+        response.clear();
+        let n = reader.read_line(&mut response).await?;
+        if n == 0 {
+            break;
+        }
+        print!("{response}");
+    }
 
     Ok(())
 }
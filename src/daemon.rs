@@ -1,25 +1,33 @@
 use anyhow::Result;
-use serde_json::{Value, json};
+use serde_json::Value;
 use std::future;
-use std::{collections::HashSet, env, sync::Arc};
 use tokio::{
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    net::{UnixListener, UnixStream},
-    process::Command,
-    sync::Mutex,
+    net::{UnixListener, UnixStream, unix::OwnedWriteHalf},
+    sync::{broadcast, mpsc, oneshot},
 };
 
-pub async fn start(sticky_windows: Arc<Mutex<HashSet<u64>>>) -> Result<()> {
-    let sticky_clone = sticky_windows.clone();
+use crate::manager::{self, Command, Rule};
+use crate::niri_ipc;
+
+pub async fn start() -> Result<()> {
+    let (tx, rx) = mpsc::channel(32);
+
     tokio::spawn(async move {
-        if let Err(e) = run_cli_server(sticky_clone).await {
+        if let Err(e) = manager::run(rx).await {
+            eprintln!("Manager error: {:?}", e);
+        }
+    });
+
+    let tx_clone = tx.clone();
+    tokio::spawn(async move {
+        if let Err(e) = run_cli_server(tx_clone).await {
             eprintln!("CLI server error: {:?}", e);
         }
     });
 
-    let sticky_clone = sticky_windows.clone();
     tokio::spawn(async move {
-        if let Err(e) = run_watcher(sticky_clone).await {
+        if let Err(e) = run_watcher(tx).await {
             eprintln!("Watcher error: {:?}", e);
         }
     });
@@ -30,26 +38,23 @@ pub async fn start(sticky_windows: Arc<Mutex<HashSet<u64>>>) -> Result<()> {
     Ok(())
 }
 
-async fn run_cli_server(sticky_windows: Arc<Mutex<HashSet<u64>>>) -> Result<()> {
+async fn run_cli_server(tx: mpsc::Sender<Command>) -> Result<()> {
     let cli_socket_path = "/tmp/niri_sticky_cli.sock";
     let _ = std::fs::remove_file(cli_socket_path);
     let listener = UnixListener::bind(cli_socket_path)?;
 
     loop {
         let (stream, _) = listener.accept().await?;
-        let sticky_clone = sticky_windows.clone();
+        let tx = tx.clone();
         tokio::spawn(async move {
-            if let Err(e) = handle_cli_connection(stream, sticky_clone).await {
+            if let Err(e) = handle_cli_connection(stream, tx).await {
                 eprintln!("CLI connection error: {:?}", e);
             }
         });
     }
 }
 
-pub async fn handle_cli_connection(
-    stream: UnixStream,
-    sticky_windows: Arc<Mutex<HashSet<u64>>>,
-) -> Result<()> {
+pub async fn handle_cli_connection(stream: UnixStream, tx: mpsc::Sender<Command>) -> Result<()> {
     let (reader, mut writer) = stream.into_split();
     let mut reader = BufReader::new(reader);
     let mut line = String::new();
@@ -59,141 +64,104 @@ pub async fn handle_cli_connection(
         return Ok(());
     }
     let line = line.trim();
-    let mut parts = line.split_whitespace();
+    let (word, rest) = line.split_once(' ').unwrap_or((line, ""));
 
-    match parts.next() {
-        Some("add") => {
-            if let Some(id_str) = parts.next() {
-                if let Ok(id) = id_str.parse::<u64>() {
-                    // 锁外检查窗口是否存在
-                    let full_window_list = get_full_window_list().await?;
-                    if !full_window_list.contains(&id) {
-                        writer.write_all(b"Window not found in Niri\n").await?;
-                        return Ok(());
-                    }
+    if word == "monitor" {
+        return run_monitor(reader, writer, tx).await;
+    }
 
-                    // 锁内添加
-                    let mut sticky = sticky_windows.lock().await;
-                    if sticky.insert(id) {
-                        writer.write_all(b"Added\n").await?;
-                    } else {
-                        writer.write_all(b"Already in sticky list\n").await?;
-                    }
-                } else {
-                    writer.write_all(b"Invalid window id\n").await?;
-                }
-            } else {
-                writer.write_all(b"Missing window id\n").await?;
-            }
-        }
+    let reply = match word {
+        "add" => match rest.trim().parse::<u64>() {
+            Ok(id) => send_command(&tx, |reply| Command::Add { id, reply }).await,
+            Err(_) => "Invalid window id".to_string(),
+        },
 
-        Some("remove") => {
-            if let Some(id_str) = parts.next() {
-                if let Ok(id) = id_str.parse::<u64>() {
-                    // 锁外检查窗口是否存在
-                    let full_window_list = get_full_window_list().await?;
-                    if !full_window_list.contains(&id) {
-                        writer.write_all(b"Window not found in Niri\n").await?;
-                        return Ok(());
-                    }
+        "remove" => match rest.trim().parse::<u64>() {
+            Ok(id) => send_command(&tx, |reply| Command::Remove { id, reply }).await,
+            Err(_) => "Invalid window id".to_string(),
+        },
 
-                    // 锁内删除
-                    let mut sticky = sticky_windows.lock().await;
-                    if sticky.remove(&id) {
-                        writer.write_all(b"Removed\n").await?;
-                    } else {
-                        writer.write_all(b"Not in sticky list\n").await?;
-                    }
-                } else {
-                    writer.write_all(b"Invalid window id\n").await?;
-                }
-            } else {
-                writer.write_all(b"Missing window id\n").await?;
-            }
-        }
+        "list" => send_command(&tx, |reply| Command::List { reply }).await,
 
-        Some("list") => {
-            // 拿锁复制快照
-            let snapshot: Vec<u64> = {
-                let sticky = sticky_windows.lock().await;
-                sticky.iter().copied().collect()
-            };
+        "toggle_active" => send_command(&tx, |reply| Command::ToggleActive { reply }).await,
 
-            // 锁外查询 niri 当前存在的窗口
-            let full_window_list = get_full_window_list().await?;
-            let valid: Vec<u64> = snapshot
-                .into_iter()
-                .filter(|id| full_window_list.contains(id))
-                .collect();
+        "stick_rule" => match serde_json::from_str::<Rule>(rest.trim()) {
+            Ok(rule) => send_command(&tx, |reply| Command::StickRule { rule, reply }).await,
+            Err(_) => "Invalid rule".to_string(),
+        },
 
-            let list_str = format!("{:?}\n", valid);
-            writer.write_all(list_str.as_bytes()).await?;
-        }
+        "unstick_rule" => match serde_json::from_str::<Rule>(rest.trim()) {
+            Ok(rule) => send_command(&tx, |reply| Command::UnstickRule { rule, reply }).await,
+            Err(_) => "Invalid rule".to_string(),
+        },
 
-        Some("toggle_active") => {
-            // 获取当前活动窗口ID
-            let active_id = match get_active_window_id().await {
-                Ok(id) => id,
-                Err(_) => {
-                    writer.write_all(b"Failed to get active window\n").await?;
-                    return Ok(());
-                }
-            };
+        _ => "Unknown command".to_string(),
+    };
 
-            // 锁外检查窗口是否存在
-            let full_window_list = get_full_window_list().await?;
-            if !full_window_list.contains(&active_id) {
-                writer
-                    .write_all(b"Active window not found in Niri\n")
-                    .await?;
-                return Ok(());
-            }
-
-            // 锁内操作 toggle
-            let mut sticky = sticky_windows.lock().await;
-            if sticky.contains(&active_id) {
-                sticky.remove(&active_id);
-                writer
-                    .write_all(b"Removed active window from sticky\n")
-                    .await?;
-            } else {
-                sticky.insert(active_id);
-                writer.write_all(b"Added active window to sticky\n").await?;
-            }
-        }
-
-        _ => {
-            writer.write_all(b"Unknown command\n").await?;
-        }
-    }
+    writer.write_all(reply.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
 
     Ok(())
 }
 
-// 获取active窗口的ID
-async fn get_active_window_id() -> Result<u64> {
-    let output = tokio::process::Command::new("niri")
-        .args(&["msg", "--json", "focused-window"])
-        .output()
-        .await?;
-
-    if !output.status.success() {
-        anyhow::bail!("Failed to get focused window");
+/// Keep the connection open and stream newline-delimited JSON events
+/// (`{"added":id}`, `{"removed":id}`, `{"moved":{"window":id,"workspace":ws}}`)
+/// for as long as the client stays connected.
+async fn run_monitor(
+    mut reader: BufReader<tokio::net::unix::OwnedReadHalf>,
+    mut writer: OwnedWriteHalf,
+    tx: mpsc::Sender<Command>,
+) -> Result<()> {
+    let (reply_tx, reply_rx) = oneshot::channel();
+    if tx.send(Command::Monitor { reply: reply_tx }).await.is_err() {
+        return Ok(());
     }
+    let Ok(mut events) = reply_rx.await else {
+        return Ok(());
+    };
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let json: serde_json::Value = serde_json::from_str(&stdout)?;
+    let mut discard = String::new();
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                match event {
+                    Ok(line) => {
+                        writer.write_all(line.as_bytes()).await?;
+                        writer.write_all(b"\n").await?;
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                }
+            }
+            n = reader.read_line(&mut discard) => {
+                if n? == 0 {
+                    return Ok(());
+                }
+                discard.clear();
+            }
+        }
+    }
+}
 
-    if let Some(id) = json.get("id").and_then(|v| v.as_u64()) {
-        Ok(id)
-    } else {
-        anyhow::bail!("Focused window id not found");
+/// Build a `Command` around a fresh oneshot reply channel, send it to the
+/// manager, and wait for the answer.
+async fn send_command<F>(tx: &mpsc::Sender<Command>, build: F) -> String
+where
+    F: FnOnce(oneshot::Sender<String>) -> Command,
+{
+    let (reply_tx, reply_rx) = oneshot::channel();
+    if tx.send(build(reply_tx)).await.is_err() {
+        return "Manager is not running".to_string();
     }
+    reply_rx
+        .await
+        .unwrap_or_else(|_| "Manager dropped the reply channel".to_string())
 }
 
-async fn run_watcher(sticky_windows: Arc<Mutex<HashSet<u64>>>) -> Result<()> {
-    let socket_path = env::var("NIRI_SOCKET").expect("NIRI_SOCKET env var not set");
-    let stream = UnixStream::connect(&socket_path).await?;
+async fn run_watcher(tx: mpsc::Sender<Command>) -> Result<()> {
+    // EventStream 是一条长期占用的连接，专门用来读取 niri 事件，
+    // 与请求/响应用的共享连接分开，避免互相阻塞读取。
+    let stream = niri_ipc::connect_socket().await?;
     let (reader, mut writer) = stream.into_split();
     let mut reader = BufReader::new(reader);
 
@@ -206,22 +174,26 @@ async fn run_watcher(sticky_windows: Arc<Mutex<HashSet<u64>>>) -> Result<()> {
         if let Ok(v) = serde_json::from_str::<Value>(&line) {
             if let Some(ws) = v.get("WorkspaceActivated") {
                 if let Some(ws_id) = ws.get("id").and_then(|id| id.as_u64()) {
-                    println!("Workspace switched to: {}", ws_id);
-
-                    let sticky_snapshot = {
-                        let mut sticky = sticky_windows.lock().await;
-                        let full_window_list = get_full_window_list().await.unwrap_or_default();
-                        sticky.retain(|win_id| full_window_list.contains(win_id));
-                        println!("Updated sticky windows: {:?}", *sticky);
-                        sticky.clone()
-                    };
-
-                    for win_id in sticky_snapshot.iter() {
-                        if let Err(e) = move_to_workspace(*win_id, ws_id).await {
-                            eprintln!("Failed to move window {}: {:?}", win_id, e);
-                        }
-                    }
+                    let _ = tx
+                        .send(Command::WorkspaceActivated {
+                            workspace_id: ws_id,
+                        })
+                        .await;
+                }
+            } else if let Some(opened) = v.get("WindowOpenedOrChanged") {
+                if let Some(id) = opened
+                    .get("window")
+                    .and_then(|w| w.get("id"))
+                    .and_then(|id| id.as_u64())
+                {
+                    let _ = tx.send(Command::WindowOpenedOrChanged { id }).await;
                 }
+            } else if let Some(id) = v
+                .get("WindowClosed")
+                .and_then(|closed| closed.get("id"))
+                .and_then(|id| id.as_u64())
+            {
+                let _ = tx.send(Command::WindowClosed { id }).await;
             }
         }
         line.clear();
@@ -229,56 +201,3 @@ async fn run_watcher(sticky_windows: Arc<Mutex<HashSet<u64>>>) -> Result<()> {
 
     Ok(())
 }
-
-async fn get_full_window_list() -> Result<HashSet<u64>> {
-    let output = Command::new("niri")
-        .args(&["msg", "--json", "windows"])
-        .output()
-        .await?;
-
-    if !output.status.success() {
-        anyhow::bail!("Failed to get windows list");
-    }
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let json: Value = serde_json::from_str(&stdout)?;
-
-    let mut window_ids = HashSet::new();
-    if let Some(arr) = json.as_array() {
-        for item in arr {
-            if let Some(id) = item.get("id").and_then(|v| v.as_u64()) {
-                window_ids.insert(id);
-            }
-        }
-    }
-
-    Ok(window_ids)
-}
-
-async fn move_to_workspace(win_id: u64, ws_id: u64) -> Result<()> {
-    let socket_path = std::env::var("NIRI_SOCKET")?;
-
-    let stream = UnixStream::connect(&socket_path).await?;
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
-
-    let cmd = json!({
-        "Action": {
-            "MoveWindowToWorkspace": {
-                "window_id": win_id,
-                "focus": false,
-                "reference": { "Id": ws_id }
-            }
-        }
-    });
-    let cmd_str = serde_json::to_string(&cmd)? + "\n";
-
-    writer.write_all(cmd_str.as_bytes()).await?;
-    writer.flush().await?;
-
-    let mut response = String::new();
-    reader.read_line(&mut response).await?;
-    println!("move_to_workspace response: {}", response.trim());
-
-    Ok(())
-}
@@ -1,9 +1,11 @@
 mod cli;
 mod daemon;
+mod manager;
+mod niri_ipc;
+mod state;
 
 use anyhow::Result;
-use std::{collections::HashSet, env, sync::Arc};
-use tokio::sync::Mutex;
+use std::env;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -13,7 +15,5 @@ async fn main() -> Result<()> {
     }
 
     // 守护进程模式
-    let sticky_windows = Arc::new(Mutex::new(HashSet::<u64>::new()));
-
-    daemon::start(sticky_windows).await
+    daemon::start().await
 }
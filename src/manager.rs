@@ -0,0 +1,495 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashSet};
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+use crate::niri_ipc;
+use crate::state::{self, WindowKey};
+
+/// A rule that auto-sticks newly-opened windows matching it. At least one
+/// of `app_id`/`title` must be set; a rule with neither would match every
+/// window.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rule {
+    /// Regex matched against the window's `app_id`.
+    pub app_id: Option<String>,
+    /// Substring matched against the window's `title`.
+    pub title: Option<String>,
+}
+
+impl Rule {
+    fn is_valid(&self) -> bool {
+        self.app_id.is_some() || self.title.is_some()
+    }
+
+    fn matches(&self, meta: &WindowMeta) -> bool {
+        let app_id_ok = match &self.app_id {
+            Some(pattern) => meta.app_id.as_deref().is_some_and(|app_id| {
+                Regex::new(pattern)
+                    .map(|re| re.is_match(app_id))
+                    .unwrap_or(false)
+            }),
+            None => true,
+        };
+        let title_ok = match &self.title {
+            Some(substring) => meta
+                .title
+                .as_deref()
+                .is_some_and(|title| title.contains(substring.as_str())),
+            None => true,
+        };
+        app_id_ok && title_ok
+    }
+}
+
+/// Everything the manager knows about a window, refreshed from niri on
+/// demand. Kept around (rather than just a `HashSet<u64>`) so sticky ids can
+/// be re-validated and turned back into `{app_id, title}` match keys for
+/// persistence without a fresh round trip for every lookup.
+#[derive(Debug, Clone)]
+struct WindowMeta {
+    workspace: Option<u64>,
+    app_id: Option<String>,
+    title: Option<String>,
+}
+
+/// Mutations and niri events accepted by the manager task. The manager owns
+/// the sticky set exclusively, so every caller goes through this channel
+/// instead of sharing a mutex across the CLI server and the watcher.
+pub enum Command {
+    Add {
+        id: u64,
+        reply: oneshot::Sender<String>,
+    },
+    Remove {
+        id: u64,
+        reply: oneshot::Sender<String>,
+    },
+    ToggleActive {
+        reply: oneshot::Sender<String>,
+    },
+    List {
+        reply: oneshot::Sender<String>,
+    },
+    WorkspaceActivated {
+        workspace_id: u64,
+    },
+    WindowOpenedOrChanged {
+        id: u64,
+    },
+    WindowClosed {
+        id: u64,
+    },
+    StickRule {
+        rule: Rule,
+        reply: oneshot::Sender<String>,
+    },
+    UnstickRule {
+        rule: Rule,
+        reply: oneshot::Sender<String>,
+    },
+    /// Subscribe to a live feed of sticky-state change events.
+    Monitor {
+        reply: oneshot::Sender<broadcast::Receiver<String>>,
+    },
+}
+
+/// Run the manager task to completion. Owns the sticky set and the
+/// last-known window map; every mutation is serialized through `rx`.
+pub async fn run(mut rx: mpsc::Receiver<Command>) -> Result<()> {
+    let (events_tx, _events_rx) = broadcast::channel(64);
+    let mut state = State {
+        sticky: HashSet::new(),
+        windows: BTreeMap::new(),
+        rules: Vec::new(),
+        active_workspace: None,
+        events_tx,
+    };
+
+    if let Err(e) = state.restore().await {
+        eprintln!("Failed to restore sticky state: {:?}", e);
+    }
+
+    while let Some(cmd) = rx.recv().await {
+        state.handle(cmd).await;
+    }
+
+    Ok(())
+}
+
+struct State {
+    sticky: HashSet<u64>,
+    windows: BTreeMap<u64, WindowMeta>,
+    rules: Vec<Rule>,
+    active_workspace: Option<u64>,
+    /// Broadcasts newline-delimited JSON events to every `monitor` subscriber.
+    events_tx: broadcast::Sender<String>,
+}
+
+impl State {
+    async fn handle(&mut self, cmd: Command) {
+        match cmd {
+            Command::Add { id, reply } => {
+                let _ = reply.send(self.add(id).await);
+            }
+            Command::Remove { id, reply } => {
+                let _ = reply.send(self.remove(id).await);
+            }
+            Command::ToggleActive { reply } => {
+                let _ = reply.send(self.toggle_active().await);
+            }
+            Command::List { reply } => {
+                let _ = reply.send(self.list().await);
+            }
+            Command::WorkspaceActivated { workspace_id } => {
+                self.workspace_activated(workspace_id).await;
+            }
+            Command::WindowOpenedOrChanged { id } => {
+                self.window_opened_or_changed(id).await;
+            }
+            Command::WindowClosed { id } => {
+                self.window_closed(id);
+            }
+            Command::StickRule { rule, reply } => {
+                let _ = reply.send(self.stick_rule(rule));
+            }
+            Command::UnstickRule { rule, reply } => {
+                let _ = reply.send(self.unstick_rule(rule));
+            }
+            Command::Monitor { reply } => {
+                let _ = reply.send(self.events_tx.subscribe());
+            }
+        }
+    }
+
+    /// Broadcast an event to every `monitor` subscriber. A no-op when
+    /// nobody's listening.
+    fn emit(&self, event: Value) {
+        let _ = self.events_tx.send(event.to_string());
+    }
+
+    async fn add(&mut self, id: u64) -> String {
+        if let Err(e) = self.refresh_windows().await {
+            return format!("Failed to query niri: {e}");
+        }
+        if !self.windows.contains_key(&id) {
+            return "Window not found in Niri".to_string();
+        }
+
+        if self.sticky.insert(id) {
+            self.persist();
+            self.emit(json!({ "added": id }));
+            "Added".to_string()
+        } else {
+            "Already in sticky list".to_string()
+        }
+    }
+
+    async fn remove(&mut self, id: u64) -> String {
+        if let Err(e) = self.refresh_windows().await {
+            return format!("Failed to query niri: {e}");
+        }
+        if !self.windows.contains_key(&id) {
+            return "Window not found in Niri".to_string();
+        }
+
+        if self.sticky.remove(&id) {
+            self.persist();
+            self.emit(json!({ "removed": id }));
+            "Removed".to_string()
+        } else {
+            "Not in sticky list".to_string()
+        }
+    }
+
+    async fn toggle_active(&mut self) -> String {
+        let active_id = match get_active_window_id().await {
+            Ok(id) => id,
+            Err(_) => return "Failed to get active window".to_string(),
+        };
+
+        if let Err(e) = self.refresh_windows().await {
+            return format!("Failed to query niri: {e}");
+        }
+        if !self.windows.contains_key(&active_id) {
+            return "Active window not found in Niri".to_string();
+        }
+
+        let msg = if self.sticky.remove(&active_id) {
+            self.emit(json!({ "removed": active_id }));
+            "Removed active window from sticky"
+        } else {
+            self.sticky.insert(active_id);
+            self.emit(json!({ "added": active_id }));
+            "Added active window to sticky"
+        };
+        self.persist();
+        msg.to_string()
+    }
+
+    async fn list(&mut self) -> String {
+        if let Err(e) = self.refresh_windows().await {
+            return format!("Failed to query niri: {e}");
+        }
+        let valid: Vec<u64> = self
+            .sticky
+            .iter()
+            .copied()
+            .filter(|id| self.windows.contains_key(id))
+            .collect();
+        format!("{:?}", valid)
+    }
+
+    async fn workspace_activated(&mut self, workspace_id: u64) {
+        println!("Workspace switched to: {}", workspace_id);
+        self.active_workspace = Some(workspace_id);
+
+        if let Err(e) = self.refresh_windows().await {
+            eprintln!("Failed to refresh window list: {:?}", e);
+            return;
+        }
+
+        let windows = &self.windows;
+        let pruned: Vec<u64> = self
+            .sticky
+            .iter()
+            .copied()
+            .filter(|id| !windows.contains_key(id))
+            .collect();
+        self.sticky.retain(|id| self.windows.contains_key(id));
+        println!("Updated sticky windows: {:?}", self.sticky);
+        if !pruned.is_empty() {
+            self.persist();
+            for id in pruned {
+                self.emit(json!({ "removed": id }));
+            }
+        }
+
+        for win_id in self.sticky.clone() {
+            if let Err(e) = move_to_workspace(win_id, workspace_id).await {
+                eprintln!("Failed to move window {}: {:?}", win_id, e);
+            } else {
+                if let Some(meta) = self.windows.get_mut(&win_id) {
+                    meta.workspace = Some(workspace_id);
+                }
+                self.emit(json!({ "moved": { "window": win_id, "workspace": workspace_id } }));
+            }
+        }
+    }
+
+    /// A window was just opened (or one of its properties changed). If it's
+    /// not already sticky and matches a registered rule, stick it and move
+    /// it onto the active workspace right away.
+    async fn window_opened_or_changed(&mut self, id: u64) {
+        if self.sticky.contains(&id) || self.rules.is_empty() {
+            return;
+        }
+
+        if let Err(e) = self.refresh_windows().await {
+            eprintln!("Failed to refresh window list: {:?}", e);
+            return;
+        }
+
+        let Some(meta) = self.windows.get(&id) else {
+            return;
+        };
+        if !self.rules.iter().any(|rule| rule.matches(meta)) {
+            return;
+        }
+
+        self.sticky.insert(id);
+        self.persist();
+        self.emit(json!({ "added": id }));
+        println!("Auto-stuck window {} via rule match", id);
+
+        if self.active_workspace.is_none() {
+            if let Ok(reply) = niri_ipc::shared().request(json!("Workspaces")).await {
+                self.active_workspace = reply
+                    .get("Ok")
+                    .and_then(|ok| ok.get("Workspaces"))
+                    .and_then(|w| w.as_array())
+                    .and_then(|arr| {
+                        arr.iter()
+                            .find(|ws| ws.get("is_focused").and_then(|f| f.as_bool()) == Some(true))
+                    })
+                    .and_then(|ws| ws.get("id"))
+                    .and_then(|id| id.as_u64());
+            }
+        }
+
+        if let Some(workspace_id) = self.active_workspace {
+            if let Err(e) = move_to_workspace(id, workspace_id).await {
+                eprintln!("Failed to move window {}: {:?}", id, e);
+            } else {
+                if let Some(meta) = self.windows.get_mut(&id) {
+                    meta.workspace = Some(workspace_id);
+                }
+                self.emit(json!({ "moved": { "window": id, "workspace": workspace_id } }));
+            }
+        }
+    }
+
+    /// A window closed; drop it from the sticky set immediately instead of
+    /// waiting for the next workspace switch to prune it.
+    fn window_closed(&mut self, id: u64) {
+        self.windows.remove(&id);
+        if self.sticky.remove(&id) {
+            self.persist();
+            self.emit(json!({ "removed": id }));
+        }
+    }
+
+    fn stick_rule(&mut self, rule: Rule) -> String {
+        if !rule.is_valid() {
+            return "Rule must set app_id and/or title".to_string();
+        }
+        if self.rules.contains(&rule) {
+            "Rule already exists".to_string()
+        } else {
+            self.rules.push(rule);
+            "Rule added".to_string()
+        }
+    }
+
+    fn unstick_rule(&mut self, rule: Rule) -> String {
+        let before = self.rules.len();
+        self.rules.retain(|r| r != &rule);
+        if self.rules.len() != before {
+            "Rule removed".to_string()
+        } else {
+            "Rule not found".to_string()
+        }
+    }
+
+    async fn refresh_windows(&mut self) -> Result<()> {
+        let windows = get_full_window_info_list().await?;
+        self.windows = windows
+            .into_iter()
+            .map(|w| {
+                (
+                    w.id,
+                    WindowMeta {
+                        workspace: w.workspace,
+                        app_id: w.app_id,
+                        title: w.title,
+                    },
+                )
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// Re-resolve the persisted `{app_id, title}` match keys against niri's
+    /// current window list to rebuild the live sticky id set.
+    async fn restore(&mut self) -> Result<()> {
+        let keys = state::load_sticky_keys()?;
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        self.refresh_windows().await?;
+        for key in &keys {
+            if let Some(&id) = self
+                .windows
+                .iter()
+                .find(|(_, w)| w.app_id == key.app_id && w.title == key.title)
+                .map(|(id, _)| id)
+            {
+                self.sticky.insert(id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Persist the current sticky set by looking up each id's `{app_id,
+    /// title}` match key and rewriting the state file.
+    fn persist(&self) {
+        let keys: Vec<WindowKey> = self
+            .sticky
+            .iter()
+            .filter_map(|id| self.windows.get(id))
+            .map(|w| WindowKey {
+                app_id: w.app_id.clone(),
+                title: w.title.clone(),
+            })
+            .collect();
+
+        if let Err(e) = state::save_sticky_keys(&keys) {
+            eprintln!("Failed to persist sticky state: {:?}", e);
+        }
+    }
+}
+
+/// A window reported by niri, along with the bits we need to re-identify it
+/// across restarts (ids are not stable across compositor restarts).
+struct WindowInfo {
+    id: u64,
+    workspace: Option<u64>,
+    app_id: Option<String>,
+    title: Option<String>,
+}
+
+// 获取active窗口的ID
+async fn get_active_window_id() -> Result<u64> {
+    let reply = niri_ipc::shared().request(json!("FocusedWindow")).await?;
+
+    let id = reply
+        .get("Ok")
+        .and_then(|ok| ok.get("FocusedWindow"))
+        .and_then(|win| win.get("id"))
+        .and_then(|v| v.as_u64());
+
+    id.context("Focused window id not found")
+}
+
+async fn get_full_window_info_list() -> Result<Vec<WindowInfo>> {
+    let reply = niri_ipc::shared().request(json!("Windows")).await?;
+
+    let arr = reply
+        .get("Ok")
+        .and_then(|ok| ok.get("Windows"))
+        .and_then(|w| w.as_array())
+        .context("malformed Windows reply from niri")?;
+
+    let mut windows = Vec::new();
+    for item in arr {
+        if let Some(id) = item.get("id").and_then(|v| v.as_u64()) {
+            let workspace = item.get("workspace_id").and_then(|v| v.as_u64());
+            let app_id = item
+                .get("app_id")
+                .and_then(|v| v.as_str())
+                .map(str::to_string);
+            let title = item
+                .get("title")
+                .and_then(|v| v.as_str())
+                .map(str::to_string);
+            windows.push(WindowInfo {
+                id,
+                workspace,
+                app_id,
+                title,
+            });
+        }
+    }
+
+    Ok(windows)
+}
+
+async fn move_to_workspace(win_id: u64, ws_id: u64) -> Result<()> {
+    let cmd = json!({
+        "Action": {
+            "MoveWindowToWorkspace": {
+                "window_id": win_id,
+                "focus": false,
+                "reference": { "Id": ws_id }
+            }
+        }
+    });
+
+    let reply = niri_ipc::shared().request(cmd).await?;
+    println!("move_to_workspace response: {}", reply);
+
+    Ok(())
+}
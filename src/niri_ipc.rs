@@ -0,0 +1,81 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::sync::OnceLock;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixStream, unix::OwnedReadHalf, unix::OwnedWriteHalf},
+    sync::Mutex,
+};
+
+/// Connect to niri's control socket, as given by `NIRI_SOCKET`.
+pub async fn connect_socket() -> Result<UnixStream> {
+    let socket_path = std::env::var("NIRI_SOCKET").context("NIRI_SOCKET env var not set")?;
+    UnixStream::connect(&socket_path)
+        .await
+        .with_context(|| format!("failed to connect to niri socket at {socket_path}"))
+}
+
+/// A reusable, reconnecting connection to niri's request/reply socket.
+///
+/// niri speaks a simple line-delimited JSON protocol: a request is a JSON
+/// value followed by `\n`, and the reply is a single JSON value on its own
+/// line. Holding the connection open avoids paying for a fresh handshake on
+/// every request, which matters on hot paths like workspace switches.
+pub struct NiriIpc {
+    conn: Mutex<Option<(BufReader<OwnedReadHalf>, OwnedWriteHalf)>>,
+}
+
+impl NiriIpc {
+    fn new() -> Self {
+        Self {
+            conn: Mutex::new(None),
+        }
+    }
+
+    /// Send one JSON request and return niri's JSON reply, connecting first
+    /// if there's no cached connection (none yet, or the previous request
+    /// failed and dropped it). A request that fails on a stale connection
+    /// returns its error; it is not retried.
+    pub async fn request(&self, request: Value) -> Result<Value> {
+        let mut guard = self.conn.lock().await;
+        if guard.is_none() {
+            let stream = connect_socket().await?;
+            let (reader, writer) = stream.into_split();
+            *guard = Some((BufReader::new(reader), writer));
+        }
+
+        let result = Self::send(guard.as_mut().unwrap(), &request).await;
+        if result.is_err() {
+            // The cached connection may have gone bad (e.g. niri restarted);
+            // drop it so the next call reconnects from scratch.
+            *guard = None;
+        }
+        result
+    }
+
+    async fn send(
+        conn: &mut (BufReader<OwnedReadHalf>, OwnedWriteHalf),
+        request: &Value,
+    ) -> Result<Value> {
+        let (reader, writer) = conn;
+        let body = serde_json::to_string(request)? + "\n";
+        writer.write_all(body.as_bytes()).await?;
+        writer.flush().await?;
+
+        let mut line = String::new();
+        let n = reader.read_line(&mut line).await?;
+        if n == 0 {
+            anyhow::bail!("niri socket closed the connection");
+        }
+        Ok(serde_json::from_str(&line)?)
+    }
+}
+
+static SHARED: OnceLock<NiriIpc> = OnceLock::new();
+
+/// The process-wide `NiriIpc` connection, shared by every caller so that
+/// repeated requests (e.g. one per CLI command, or per workspace switch)
+/// reuse the same socket instead of reconnecting each time.
+pub fn shared() -> &'static NiriIpc {
+    SHARED.get_or_init(NiriIpc::new)
+}
@@ -0,0 +1,55 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// A match key used to re-identify a window across niri/daemon restarts,
+/// since niri reassigns window ids whenever the compositor restarts.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WindowKey {
+    pub app_id: Option<String>,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PersistedState {
+    sticky: Vec<WindowKey>,
+}
+
+/// Resolve `~/.local/state/nsticky/state.json` (or the platform equivalent),
+/// creating the parent directory if it doesn't exist yet.
+fn state_file_path() -> Result<PathBuf> {
+    let mut dir = dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .context("could not resolve a state directory for this platform")?;
+    dir.push("nsticky");
+    std::fs::create_dir_all(&dir)?;
+    dir.push("state.json");
+    Ok(dir)
+}
+
+/// Load the persisted sticky set. Returns an empty list if no state file
+/// exists yet (e.g. first run).
+pub fn load_sticky_keys() -> Result<Vec<WindowKey>> {
+    let path = state_file_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let data = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read state file {}", path.display()))?;
+    let state: PersistedState = serde_json::from_str(&data)
+        .with_context(|| format!("failed to parse state file {}", path.display()))?;
+    Ok(state.sticky)
+}
+
+/// Overwrite the state file with the given set of match keys.
+pub fn save_sticky_keys(keys: &[WindowKey]) -> Result<()> {
+    let path = state_file_path()?;
+    let state = PersistedState {
+        sticky: keys.to_vec(),
+    };
+    let data = serde_json::to_string_pretty(&state)?;
+    std::fs::write(&path, data)
+        .with_context(|| format!("failed to write state file {}", path.display()))?;
+    Ok(())
+}